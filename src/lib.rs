@@ -0,0 +1,1445 @@
+//! Reusable PNG chunk/scanline primitives and the `catpng` concatenation/animation core.
+//!
+//! Everything here is ignorant of the CLI: inputs are anything implementing `Read + Seek`, not
+//! file paths, and the buffer-based [`catpng_into`] variant lets a caller avoid handing this
+//! crate an allocator of its own for the (usually dominant) combined pixel buffer. This is still
+//! a `std` crate, though: chunk I/O goes through `std::io::{Read, Seek, Write}`, and each input's
+//! zlib decompression (and the final re-compression) allocates its own `Vec` internally, so only
+//! the merged buffer's allocation is caller-controlled, not the whole pipeline's.
+
+use std::io::{self, Cursor, Read, Seek, Write};
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use crc32fast::Hasher;
+use miniz_oxide::deflate::compress_to_vec_zlib;
+use miniz_oxide::inflate::{decompress_to_vec_zlib, DecompressError};
+use thiserror::Error;
+
+const PNG_SIGNATURE: &[u8; 8] = b"\x89PNG\x0D\x0A\x1A\x0A";
+
+#[derive(Error, Debug)]
+pub enum PngError {
+    #[error("IO error")]
+    Io(#[from] io::Error),
+    #[error("Decompress error")]
+    Decompress(#[from] DecompressError),
+    #[error("invalid PNG file signature")]
+    InvalidSignature,
+    #[error("invalid chunk type code {0:?}")]
+    InvalidTypeCode([u8; 4]),
+    #[error("invalid IHDR length")]
+    InvalidIhdrLength,
+    #[error("chunk type code is not IHDR")]
+    NotIhdr,
+    #[error("png width is not equal to the first's")]
+    UnequalWidth,
+    #[error("unsupported color type {0}")]
+    UnsupportedColorType(u8),
+    #[error("decompressed image data length does not match IHDR dimensions")]
+    InvalidImageDataLength,
+    #[error("unsupported scanline filter type {0}")]
+    UnsupportedFilterType(u8),
+    #[error("bad CRC for {kind:?} chunk: expected {expected:#010x}, found {found:#010x}")]
+    BadCrc {
+        kind: PngChunkKind,
+        expected: u32,
+        found: u32,
+    },
+    #[error("PLTE/tRNS chunk is not equal to the first's")]
+    UnequalPalette,
+    #[error("png height is not equal to the first's")]
+    UnequalHeight,
+    #[error("png bit depth/color type is not equal to the first's")]
+    UnequalColorFormat,
+    #[error("buffer too small: need {required} bytes, got {actual}")]
+    BufferTooSmall { required: usize, actual: usize },
+    #[error("indexed-color (color_type 3) output has no PLTE chunk")]
+    MissingPalette,
+    #[error("no input images given")]
+    NoInputImages,
+}
+
+/// Whether chunk CRCs are verified on read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CrcMode {
+    /// Reject any chunk whose stored CRC doesn't match the computed one.
+    Strict,
+    /// Ignore CRC mismatches, for recovering slightly damaged files.
+    Lenient,
+}
+
+/// Which axis `catpng` stacks input images along.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConcatAxis {
+    /// Stack images top-to-bottom; inputs must share a width and color format.
+    Vertical,
+    /// Place images side-by-side; inputs must share a height and color format.
+    Horizontal,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum PngChunkKind {
+    Ihdr,
+    Idat,
+    Iend,
+    /// Any other chunk type, critical or ancillary, carrying its raw type code so it can be
+    /// round-tripped through `PngChunk::write` without the reader understanding its contents.
+    Other([u8; 4]),
+}
+
+impl PngChunkKind {
+    const IHDR: &'static [u8; 4] = b"IHDR";
+    const IDAT: &'static [u8; 4] = b"IDAT";
+    const IEND: &'static [u8; 4] = b"IEND";
+    const PLTE: &'static [u8; 4] = b"PLTE";
+    const TRNS: &'static [u8; 4] = b"tRNS";
+    const ACTL: &'static [u8; 4] = b"acTL";
+    const FCTL: &'static [u8; 4] = b"fcTL";
+    const FDAT: &'static [u8; 4] = b"fdAT";
+
+    fn type_code(&self) -> [u8; 4] {
+        match self {
+            PngChunkKind::Ihdr => *Self::IHDR,
+            PngChunkKind::Idat => *Self::IDAT,
+            PngChunkKind::Iend => *Self::IEND,
+            PngChunkKind::Other(code) => *code,
+        }
+    }
+}
+
+impl TryFrom<&[u8; 4]> for PngChunkKind {
+    type Error = PngError;
+    fn try_from(type_code: &[u8; 4]) -> Result<Self, Self::Error> {
+        use PngChunkKind::*;
+        match type_code {
+            PngChunkKind::IHDR => Ok(Ihdr),
+            PngChunkKind::IDAT => Ok(Idat),
+            PngChunkKind::IEND => Ok(Iend),
+            _ if type_code.iter().all(u8::is_ascii_alphabetic) => Ok(Other(*type_code)),
+            _ => Err(PngError::InvalidTypeCode(*type_code)),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct PngChunk {
+    pub kind: PngChunkKind,
+    pub data: Box<[u8]>,
+}
+
+trait ReadExactExt: Read {
+    #[inline]
+    fn read_exact_capacity(&mut self, buf: &mut Vec<u8>) -> io::Result<usize> {
+        self.take(buf.capacity() as u64)
+            .read_to_end(buf)
+            .and_then(|n| {
+                if n < buf.capacity() {
+                    Err(io::Error::from(io::ErrorKind::UnexpectedEof))
+                } else {
+                    Ok(n)
+                }
+            })
+    }
+}
+
+impl<R: Read> ReadExactExt for R {}
+
+impl PngChunk {
+    fn new<T: Read + Seek>(reader: &mut T, crc_mode: CrcMode) -> Result<Self, PngError> {
+        let mut buf = [0u8; 4];
+
+        let length = reader.read_u32::<BigEndian>()? as usize;
+
+        reader.read_exact(&mut buf)?;
+        let kind = PngChunkKind::try_from(&buf)?;
+        let type_code = buf;
+
+        if kind == PngChunkKind::Ihdr && length != 13 {
+            return Err(PngError::InvalidIhdrLength);
+        }
+
+        // Optimization: Only performs one allocation for the data buffer
+        let mut data = Vec::new();
+        data.reserve_exact(length);
+        reader.read_exact_capacity(&mut data)?;
+
+        let stored_crc = reader.read_u32::<BigEndian>()?;
+        if crc_mode == CrcMode::Strict {
+            let mut hasher = Hasher::new();
+            hasher.update(&type_code);
+            hasher.update(&data);
+            let computed_crc = hasher.finalize();
+
+            if computed_crc != stored_crc {
+                return Err(PngError::BadCrc {
+                    kind,
+                    expected: stored_crc,
+                    found: computed_crc,
+                });
+            }
+        }
+
+        Ok(Self {
+            kind,
+            data: data.into_boxed_slice(),
+        })
+    }
+
+    fn write<T: Write>(&self, writer: &mut T) -> io::Result<()> {
+        writer.write_u32::<BigEndian>(self.data.len() as u32)?;
+
+        let type_code = self.kind.type_code();
+        writer.write_all(&type_code)?;
+
+        writer.write_all(&self.data)?;
+
+        let mut hasher = Hasher::new();
+        hasher.update(&type_code);
+        hasher.update(&self.data);
+        writer.write_u32::<BigEndian>(hasher.finalize())?;
+
+        Ok(())
+    }
+
+    fn iend() -> Self {
+        Self {
+            kind: PngChunkKind::Iend,
+            data: Box::new([]),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct IhdrData {
+    pub width: u32,
+    pub height: u32,
+    pub bit_depth: u8,
+    pub color_type: u8,
+    pub compression: u8,
+    pub filter: u8,
+    pub interlace: u8,
+}
+
+impl IhdrData {
+    /// Number of samples per pixel implied by `color_type`, per the PNG spec.
+    fn channels(&self) -> Result<u8, PngError> {
+        match self.color_type {
+            0 | 3 => Ok(1), // grayscale, indexed
+            4 => Ok(2),     // grayscale + alpha
+            2 => Ok(3),     // RGB
+            6 => Ok(4),     // RGBA
+            _ => Err(PngError::UnsupportedColorType(self.color_type)),
+        }
+    }
+
+    /// Distance, in bytes, back to the pixel to the left of a given scanline byte.
+    fn bytes_per_pixel(&self) -> Result<usize, PngError> {
+        let bits = self.bit_depth as usize * self.channels()? as usize;
+        Ok(bits.div_ceil(8).max(1))
+    }
+
+    /// Bytes in one reconstructed scanline, excluding the leading filter type byte.
+    fn row_stride(&self) -> Result<usize, PngError> {
+        let bits = self.width as usize * self.bit_depth as usize * self.channels()? as usize;
+        Ok(bits.div_ceil(8))
+    }
+
+    /// Bytes in one raw (still filtered) scanline, including the leading filter type byte.
+    fn scanline_len(&self) -> Result<usize, PngError> {
+        Ok(1 + self.row_stride()?)
+    }
+
+    /// Exact size, in bytes, of this image's decompressed (but still filtered) scanline data,
+    /// i.e. what `decompress_to_vec_zlib` yields for one IDAT stream:
+    /// `height * (1 + ceil(width * bit_depth * channels / 8))`.
+    pub fn required_bytes(&self) -> Result<usize, PngError> {
+        Ok(self.height as usize * self.scanline_len()?)
+    }
+}
+
+impl TryFrom<&PngChunk> for IhdrData {
+    type Error = PngError;
+
+    fn try_from(chunk: &PngChunk) -> Result<Self, Self::Error> {
+        if chunk.kind != PngChunkKind::Ihdr {
+            return Err(PngError::NotIhdr);
+        }
+
+        let mut cursor = Cursor::new(chunk.data.as_ref());
+        Ok(IhdrData {
+            width: cursor.read_u32::<BigEndian>()?,
+            height: cursor.read_u32::<BigEndian>()?,
+            bit_depth: cursor.read_u8()?,
+            color_type: cursor.read_u8()?,
+            compression: cursor.read_u8()?,
+            filter: cursor.read_u8()?,
+            interlace: cursor.read_u8()?,
+        })
+    }
+}
+
+impl From<IhdrData> for PngChunk {
+    fn from(ihdr: IhdrData) -> Self {
+        // Optimization: Only performs one allocation for the data buffer
+        let mut data = Vec::new();
+        data.reserve_exact(13);
+
+        // Convert to Options here to ignore Result without using let _ = ... since that's too aggressive
+        data.write_u32::<BigEndian>(ihdr.width).ok();
+        data.write_u32::<BigEndian>(ihdr.height).ok();
+        data.write_u8(ihdr.bit_depth).ok();
+        data.write_u8(ihdr.color_type).ok();
+        data.write_u8(ihdr.compression).ok();
+        data.write_u8(ihdr.filter).ok();
+        data.write_u8(ihdr.interlace).ok();
+
+        PngChunk {
+            kind: PngChunkKind::Ihdr,
+            data: data.into_boxed_slice(),
+        }
+    }
+}
+
+/// Reconstructs the Paeth predictor byte for samples `a` (left), `b` (above) and `c` (above-left).
+fn paeth_predictor(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let pa = (p - a as i16).abs();
+    let pb = (p - b as i16).abs();
+    let pc = (p - c as i16).abs();
+
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+/// Undoes the per-scanline filtering described in the PNG spec, returning the raw pixel
+/// samples: `filtered` with every leading filter type byte stripped and every filter undone.
+fn unfilter_scanlines(ihdr: &IhdrData, filtered: &[u8]) -> Result<Vec<u8>, PngError> {
+    let bpp = ihdr.bytes_per_pixel()?;
+    let stride = ihdr.row_stride()?;
+    let height = ihdr.height as usize;
+
+    if filtered.len() != ihdr.scanline_len()? * height {
+        return Err(PngError::InvalidImageDataLength);
+    }
+
+    let mut raw = vec![0u8; stride * height];
+    for row in 0..height {
+        let scanline = &filtered[row * (1 + stride)..(row + 1) * (1 + stride)];
+        let filter_type = scanline[0];
+        let src = &scanline[1..];
+
+        let (above, cur) = raw.split_at_mut(row * stride);
+        let cur = &mut cur[..stride];
+        let above = (row > 0).then(|| &above[(row - 1) * stride..row * stride]);
+
+        for i in 0..stride {
+            let a = if i >= bpp { cur[i - bpp] } else { 0 };
+            let b = above.map_or(0, |r| r[i]);
+            let c = if i >= bpp {
+                above.map_or(0, |r| r[i - bpp])
+            } else {
+                0
+            };
+
+            cur[i] = match filter_type {
+                0 => src[i],
+                1 => src[i].wrapping_add(a),
+                2 => src[i].wrapping_add(b),
+                3 => src[i].wrapping_add(((a as u16 + b as u16) / 2) as u8),
+                4 => src[i].wrapping_add(paeth_predictor(a, b, c)),
+                _ => return Err(PngError::UnsupportedFilterType(filter_type)),
+            };
+        }
+    }
+
+    Ok(raw)
+}
+
+/// Re-applies scanline filtering to raw pixel samples, always choosing filter type 0 (None) since
+/// it's always valid and cheapest to compute.
+fn filter_scanlines(ihdr: &IhdrData, raw: &[u8]) -> Result<Vec<u8>, PngError> {
+    let stride = ihdr.row_stride()?;
+
+    let mut filtered = Vec::with_capacity(raw.len() + ihdr.height as usize);
+    for row in raw.chunks(stride) {
+        filtered.push(0u8);
+        filtered.extend_from_slice(row);
+    }
+
+    Ok(filtered)
+}
+
+/// Checks that a PLTE or tRNS chunk's data matches the copy already saved from an earlier input,
+/// saving it instead if this is the first one seen. On a non-indexed image PLTE is merely a
+/// suggested palette, not a spec requirement to match across inputs, so `strict` skips the
+/// equality check (still saving the first copy seen, to carry into the output) in that case.
+fn save_or_check_palette_chunk(
+    saved: &mut Option<Box<[u8]>>,
+    data: &[u8],
+    strict: bool,
+) -> Result<(), PngError> {
+    match saved {
+        Some(saved) if strict && saved.as_ref() != data => Err(PngError::UnequalPalette),
+        Some(_) => Ok(()),
+        None => {
+            *saved = Some(data.into());
+            Ok(())
+        }
+    }
+}
+
+/// Unpacks `width` samples of `bit_depth` bits each from one channels=1 scanline (bit_depth < 8).
+fn unpack_sub_byte_samples(row: &[u8], width: u32, bit_depth: u8) -> Vec<u8> {
+    let mask = (1u16 << bit_depth) - 1;
+    (0..width as usize)
+        .map(|i| {
+            let bit_idx = i * bit_depth as usize;
+            let shift = 8 - (bit_idx % 8) - bit_depth as usize;
+            ((row[bit_idx / 8] as u16 >> shift) & mask) as u8
+        })
+        .collect()
+}
+
+/// Packs samples of `bit_depth` bits each (bit_depth < 8) back into a scanline, most significant
+/// bits first, filling the final byte's unused low-order bits with zeros if
+/// `samples.len() * bit_depth` isn't a multiple of 8.
+fn pack_sub_byte_samples(samples: &[u8], bit_depth: u8) -> Vec<u8> {
+    let mut out = Vec::with_capacity((samples.len() * bit_depth as usize).div_ceil(8));
+    let mut cur = 0u8;
+    let mut bits_filled = 0u8;
+
+    for &sample in samples {
+        cur |= sample << (8 - bits_filled - bit_depth);
+        bits_filled += bit_depth;
+        if bits_filled == 8 {
+            out.push(cur);
+            cur = 0;
+            bits_filled = 0;
+        }
+    }
+    if bits_filled > 0 {
+        out.push(cur);
+    }
+
+    out
+}
+
+/// Concatenates the corresponding row from each of `rows` (in order) into one output row,
+/// accounting for sub-byte (`bit_depth` < 8) grayscale/indexed scanlines packing no padding
+/// between samples except at the very end of the row.
+fn concat_row(rows: &[(&[u8], u32)], bit_depth: u8) -> Vec<u8> {
+    if bit_depth >= 8 {
+        return rows
+            .iter()
+            .flat_map(|(row, _)| row.iter().copied())
+            .collect();
+    }
+
+    let samples: Vec<u8> = rows
+        .iter()
+        .flat_map(|(row, width)| unpack_sub_byte_samples(row, *width, bit_depth))
+        .collect();
+    pack_sub_byte_samples(&samples, bit_depth)
+}
+
+/// Reads one input PNG's IHDR and fully unfiltered pixel data, checking any PLTE/tRNS chunks it
+/// carries against the ones already seen from earlier inputs.
+fn parse_png<U: Read + Seek>(
+    mut png: U,
+    crc_mode: CrcMode,
+    plte: &mut Option<Box<[u8]>>,
+    trns: &mut Option<Box<[u8]>>,
+) -> Result<(IhdrData, Vec<u8>), PngError> {
+    let mut signature_buf = [0u8; PNG_SIGNATURE.len()];
+    if png.read_exact(&mut signature_buf).is_err() || signature_buf != *PNG_SIGNATURE {
+        return Err(PngError::InvalidSignature);
+    }
+
+    let ihdr = IhdrData::try_from(&PngChunk::new(&mut png, crc_mode)?)?;
+
+    // A PNG's compressed image data may be split across any number of consecutive IDAT chunks,
+    // so keep reading chunks (tolerating anything in between) until IEND.
+    let mut compressed = Vec::new();
+    loop {
+        let chunk = PngChunk::new(&mut png, crc_mode)?;
+        match chunk.kind {
+            PngChunkKind::Idat => compressed.extend_from_slice(&chunk.data),
+            PngChunkKind::Iend => break,
+            PngChunkKind::Other(code) if &code == PngChunkKind::PLTE => {
+                save_or_check_palette_chunk(plte, &chunk.data, ihdr.color_type == 3)?;
+            }
+            PngChunkKind::Other(code) if &code == PngChunkKind::TRNS => {
+                save_or_check_palette_chunk(trns, &chunk.data, ihdr.color_type == 3)?;
+            }
+            PngChunkKind::Ihdr | PngChunkKind::Other(_) => {}
+        }
+    }
+
+    let filtered = decompress_to_vec_zlib(&compressed)?;
+    let raw = unfilter_scanlines(&ihdr, &filtered)?;
+
+    Ok((ihdr, raw))
+}
+
+/// Every input's IHDR and unfiltered pixel data, plus any shared PLTE/tRNS chunk carried over.
+type ParsedImages = (
+    Vec<(IhdrData, Vec<u8>)>,
+    Option<Box<[u8]>>,
+    Option<Box<[u8]>>,
+);
+
+/// Parses every input, collecting each one's IHDR and unfiltered pixel data, and checking any
+/// PLTE/tRNS chunks they carry against each other.
+fn parse_images<T, U>(pngs: T, crc_mode: CrcMode) -> Result<ParsedImages, PngError>
+where
+    T: IntoIterator<Item = U>,
+    U: Read + Seek,
+{
+    let mut plte = None;
+    let mut trns = None;
+
+    let images = pngs
+        .into_iter()
+        .map(|reader| parse_png(reader, crc_mode, &mut plte, &mut trns))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok((images, plte, trns))
+}
+
+/// Builds the ancillary chunks to carry over into a merged output: one PLTE and/or tRNS chunk if
+/// any input carried one.
+fn ancillary_chunks(plte: Option<Box<[u8]>>, trns: Option<Box<[u8]>>) -> Vec<PngChunk> {
+    [(PngChunkKind::PLTE, plte), (PngChunkKind::TRNS, trns)]
+        .into_iter()
+        .filter_map(|(type_code, data)| {
+            data.map(|data| PngChunk {
+                kind: PngChunkKind::Other(*type_code),
+                data,
+            })
+        })
+        .collect()
+}
+
+/// Checks that an indexed-color (`color_type == 3`) output actually has a PLTE chunk to carry
+/// over; writing one out without a palette would be undecodable per the PNG spec.
+fn check_palette(ihdr: &IhdrData, plte: &Option<Box<[u8]>>) -> Result<(), PngError> {
+    if ihdr.color_type == 3 && plte.is_none() {
+        return Err(PngError::MissingPalette);
+    }
+    Ok(())
+}
+
+/// Validates `images` against `axis`'s constraints and computes the merged output's IHDR.
+fn combined_ihdr(images: &[(IhdrData, Vec<u8>)], axis: ConcatAxis) -> Result<IhdrData, PngError> {
+    let mut ihdr: Option<IhdrData> = None;
+    for (image_ihdr, _) in images {
+        match (&mut ihdr, axis) {
+            (Some(ihdr), ConcatAxis::Vertical) if ihdr.width != image_ihdr.width => {
+                return Err(PngError::UnequalWidth)
+            }
+            (Some(ihdr), ConcatAxis::Vertical)
+                if ihdr.bit_depth != image_ihdr.bit_depth
+                    || ihdr.color_type != image_ihdr.color_type =>
+            {
+                return Err(PngError::UnequalColorFormat)
+            }
+            (Some(ihdr), ConcatAxis::Vertical) => ihdr.height += image_ihdr.height,
+            (Some(ihdr), ConcatAxis::Horizontal) if ihdr.height != image_ihdr.height => {
+                return Err(PngError::UnequalHeight)
+            }
+            (Some(ihdr), ConcatAxis::Horizontal)
+                if ihdr.bit_depth != image_ihdr.bit_depth
+                    || ihdr.color_type != image_ihdr.color_type =>
+            {
+                return Err(PngError::UnequalColorFormat)
+            }
+            (Some(ihdr), ConcatAxis::Horizontal) => ihdr.width += image_ihdr.width,
+            (None, _) => ihdr = Some(*image_ihdr),
+        }
+    }
+
+    ihdr.ok_or(PngError::NoInputImages)
+}
+
+/// Writes the merged output's filtered (filter type 0) scanline data into `out`, which must be
+/// exactly `ihdr.required_bytes()` long.
+fn write_combined_filtered(
+    images: &[(IhdrData, Vec<u8>)],
+    ihdr: &IhdrData,
+    axis: ConcatAxis,
+    out: &mut [u8],
+) -> Result<(), PngError> {
+    let stride = ihdr.row_stride()?;
+
+    match axis {
+        ConcatAxis::Vertical => {
+            let mut pos = 0;
+            for (image_ihdr, raw) in images {
+                for row in raw.chunks(image_ihdr.row_stride()?) {
+                    out[pos] = 0;
+                    out[pos + 1..pos + 1 + stride].copy_from_slice(row);
+                    pos += 1 + stride;
+                }
+            }
+        }
+        ConcatAxis::Horizontal => {
+            let strides = images
+                .iter()
+                .map(|(image_ihdr, _)| image_ihdr.row_stride())
+                .collect::<Result<Vec<_>, _>>()?;
+
+            for r in 0..ihdr.height as usize {
+                let rows: Vec<(&[u8], u32)> = images
+                    .iter()
+                    .zip(&strides)
+                    .map(|((image_ihdr, image_raw), &s)| {
+                        (&image_raw[r * s..(r + 1) * s], image_ihdr.width)
+                    })
+                    .collect();
+                let row = concat_row(&rows, ihdr.bit_depth);
+
+                let pos = r * (1 + stride);
+                out[pos] = 0;
+                out[pos + 1..pos + 1 + stride].copy_from_slice(&row);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Concatenates `pngs` (each an independent PNG) along `axis` into one merged image, choosing
+/// filter type 0 (None) and the given zlib `level` when re-encoding.
+pub fn catpng<T, U>(
+    pngs: T,
+    level: u8,
+    crc_mode: CrcMode,
+    axis: ConcatAxis,
+) -> Result<(IhdrData, Vec<PngChunk>, PngChunk), PngError>
+where
+    T: IntoIterator<Item = U>,
+    U: Read + Seek,
+{
+    let (images, plte, trns) = parse_images(pngs, crc_mode)?;
+    let ihdr = combined_ihdr(&images, axis)?;
+    check_palette(&ihdr, &plte)?;
+
+    let mut filtered = vec![0u8; ihdr.required_bytes()?];
+    write_combined_filtered(&images, &ihdr, axis, &mut filtered)?;
+
+    Ok((
+        ihdr,
+        ancillary_chunks(plte, trns),
+        PngChunk {
+            kind: PngChunkKind::Idat,
+            data: compress_to_vec_zlib(&filtered, level).into_boxed_slice(),
+        },
+    ))
+}
+
+/// Like [`catpng`], but decodes the merged image's filtered scanline data into `buf` instead of
+/// allocating it, for callers that control their own memory footprint (`buf` need only be
+/// [`IhdrData::required_bytes`] of the expected merged output). Returns
+/// [`PngError::BufferTooSmall`] if `buf` isn't big enough.
+pub fn catpng_into<T, U>(
+    pngs: T,
+    level: u8,
+    crc_mode: CrcMode,
+    axis: ConcatAxis,
+    buf: &mut [u8],
+) -> Result<(IhdrData, Vec<PngChunk>, PngChunk), PngError>
+where
+    T: IntoIterator<Item = U>,
+    U: Read + Seek,
+{
+    let (images, plte, trns) = parse_images(pngs, crc_mode)?;
+    let ihdr = combined_ihdr(&images, axis)?;
+    check_palette(&ihdr, &plte)?;
+
+    let required = ihdr.required_bytes()?;
+    if buf.len() < required {
+        return Err(PngError::BufferTooSmall {
+            required,
+            actual: buf.len(),
+        });
+    }
+    let filtered = &mut buf[..required];
+    write_combined_filtered(&images, &ihdr, axis, filtered)?;
+
+    Ok((
+        ihdr,
+        ancillary_chunks(plte, trns),
+        PngChunk {
+            kind: PngChunkKind::Idat,
+            data: compress_to_vec_zlib(filtered, level).into_boxed_slice(),
+        },
+    ))
+}
+
+pub fn write_png<T: Write>(
+    (ihdr, ancillary, idat): (IhdrData, Vec<PngChunk>, PngChunk),
+    writer: &mut T,
+) -> io::Result<()> {
+    writer.write_all(PNG_SIGNATURE)?;
+    PngChunk::from(ihdr).write(writer)?;
+    for c in ancillary {
+        c.write(writer)?;
+    }
+    idat.write(writer)?;
+    PngChunk::iend().write(writer)?;
+
+    Ok(())
+}
+
+/// Builds the `acTL` chunk announcing an APNG's frame count (infinite loop count).
+fn actl_chunk(num_frames: u32) -> PngChunk {
+    let mut data = Vec::new();
+    data.reserve_exact(8);
+    data.write_u32::<BigEndian>(num_frames).ok();
+    data.write_u32::<BigEndian>(0).ok(); // num_plays: 0 = loop forever
+
+    PngChunk {
+        kind: PngChunkKind::Other(*PngChunkKind::ACTL),
+        data: data.into_boxed_slice(),
+    }
+}
+
+/// Builds one frame's `fcTL` chunk: full-image frame at offset 0/0, replacing the prior frame
+/// outright (`dispose_op` = NONE, `blend_op` = SOURCE).
+fn fctl_chunk(
+    seq: u32,
+    ihdr: &IhdrData,
+    delay_num: u16,
+    delay_den: u16,
+) -> Result<PngChunk, PngError> {
+    let mut data = Vec::new();
+    data.reserve_exact(26);
+    data.write_u32::<BigEndian>(seq)?;
+    data.write_u32::<BigEndian>(ihdr.width)?;
+    data.write_u32::<BigEndian>(ihdr.height)?;
+    data.write_u32::<BigEndian>(0)?; // x_offset
+    data.write_u32::<BigEndian>(0)?; // y_offset
+    data.write_u16::<BigEndian>(delay_num)?;
+    data.write_u16::<BigEndian>(delay_den)?;
+    data.write_u8(0)?; // dispose_op: APNG_DISPOSE_OP_NONE
+    data.write_u8(0)?; // blend_op: APNG_BLEND_OP_SOURCE
+
+    Ok(PngChunk {
+        kind: PngChunkKind::Other(*PngChunkKind::FCTL),
+        data: data.into_boxed_slice(),
+    })
+}
+
+/// Builds a non-first frame's `fdAT` chunk: the sequence number followed by its zlib stream.
+fn fdat_chunk(seq: u32, compressed: &[u8]) -> PngChunk {
+    let mut data = Vec::with_capacity(4 + compressed.len());
+    data.write_u32::<BigEndian>(seq).ok();
+    data.extend_from_slice(compressed);
+
+    PngChunk {
+        kind: PngChunkKind::Other(*PngChunkKind::FDAT),
+        data: data.into_boxed_slice(),
+    }
+}
+
+/// Builds the IHDR plus every body chunk (any shared PLTE/tRNS, then `acTL`, and each frame's
+/// `fcTL` paired with its IDAT or `fdAT`) for an APNG that treats each input PNG as one animation frame, in file order. All
+/// frames must share a width, height and color format.
+pub fn apng<T, U>(
+    pngs: T,
+    level: u8,
+    crc_mode: CrcMode,
+    delay_num: u16,
+    delay_den: u16,
+) -> Result<(IhdrData, Vec<PngChunk>), PngError>
+where
+    T: IntoIterator<Item = U>,
+    U: Read + Seek,
+{
+    let (images, plte, trns) = parse_images(pngs, crc_mode)?;
+
+    let ihdr = images.first().ok_or(PngError::NoInputImages)?.0;
+    for (image_ihdr, _) in &images {
+        if image_ihdr.width != ihdr.width {
+            return Err(PngError::UnequalWidth);
+        }
+        if image_ihdr.height != ihdr.height {
+            return Err(PngError::UnequalHeight);
+        }
+        if image_ihdr.bit_depth != ihdr.bit_depth || image_ihdr.color_type != ihdr.color_type {
+            return Err(PngError::UnequalColorFormat);
+        }
+    }
+    check_palette(&ihdr, &plte)?;
+
+    let mut seq = 0u32;
+    let mut body = ancillary_chunks(plte, trns);
+    body.push(actl_chunk(images.len() as u32));
+
+    for (i, (_, raw)) in images.into_iter().enumerate() {
+        let filtered = filter_scanlines(&ihdr, &raw)?;
+        let compressed = compress_to_vec_zlib(&filtered, level);
+
+        body.push(fctl_chunk(seq, &ihdr, delay_num, delay_den)?);
+        seq += 1;
+
+        if i == 0 {
+            body.push(PngChunk {
+                kind: PngChunkKind::Idat,
+                data: compressed.into_boxed_slice(),
+            });
+        } else {
+            body.push(fdat_chunk(seq, &compressed));
+            seq += 1;
+        }
+    }
+
+    Ok((ihdr, body))
+}
+
+pub fn write_apng<T: Write>(
+    (ihdr, body): (IhdrData, Vec<PngChunk>),
+    writer: &mut T,
+) -> io::Result<()> {
+    writer.write_all(PNG_SIGNATURE)?;
+    PngChunk::from(ihdr).write(writer)?;
+    for c in body {
+        c.write(writer)?;
+    }
+    PngChunk::iend().write(writer)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    macro_rules! ihdr {
+        { $w:expr, $h:expr, $b:expr, $c:expr, $z:expr, $f:expr, $i:expr } => {
+            IhdrData {
+                width: $w,
+                height: $h,
+                bit_depth: $b,
+                color_type: $c,
+                compression: $z,
+                filter: $f,
+                interlace: $i,
+            }
+        };
+    }
+
+    /// Prefixes every `stride`-sized row in `raw` with a filter type 0 (None) byte, mirroring
+    /// what `filter_scanlines` produces.
+    fn as_filtered(raw: &[u8], stride: usize) -> Vec<u8> {
+        let mut out = Vec::new();
+        for row in raw.chunks(stride) {
+            out.push(0u8);
+            out.extend_from_slice(row);
+        }
+        out
+    }
+
+    /// Builds a one-IDAT PNG out of already-filtered scanline bytes (i.e. `filtered` must
+    /// already include the leading filter type byte on each row).
+    fn png_vec(ihdr: IhdrData, filtered: &[u8], level: u8) -> Result<Vec<u8>, PngError> {
+        let mut buf = Vec::new();
+        write_png(
+            (
+                ihdr,
+                Vec::new(),
+                PngChunk {
+                    kind: PngChunkKind::Idat,
+                    data: compress_to_vec_zlib(filtered, level).into_boxed_slice(),
+                },
+            ),
+            &mut buf,
+        )?;
+
+        Ok(buf)
+    }
+
+    /// Builds a PNG whose compressed image data is split across `n` consecutive IDAT chunks.
+    fn png_vec_split_idat(
+        ihdr: IhdrData,
+        filtered: &[u8],
+        level: u8,
+        n: usize,
+    ) -> Result<Vec<u8>, PngError> {
+        let compressed = compress_to_vec_zlib(filtered, level);
+        let chunk_len = compressed.len().div_ceil(n).max(1);
+
+        let mut buf = Vec::new();
+        buf.write_all(PNG_SIGNATURE)?;
+        PngChunk::from(ihdr).write(&mut buf)?;
+        for data in compressed.chunks(chunk_len) {
+            PngChunk {
+                kind: PngChunkKind::Idat,
+                data: data.into(),
+            }
+            .write(&mut buf)?;
+        }
+        PngChunk::iend().write(&mut buf)?;
+
+        Ok(buf)
+    }
+
+    /// Builds an indexed-color PNG carrying a PLTE chunk (and an optional tRNS chunk) before IDAT.
+    fn png_vec_with_palette(
+        ihdr: IhdrData,
+        filtered: &[u8],
+        plte: &[u8],
+        trns: Option<&[u8]>,
+        level: u8,
+    ) -> Result<Vec<u8>, PngError> {
+        let mut buf = Vec::new();
+        buf.write_all(PNG_SIGNATURE)?;
+        PngChunk::from(ihdr).write(&mut buf)?;
+        PngChunk {
+            kind: PngChunkKind::Other(*PngChunkKind::PLTE),
+            data: plte.into(),
+        }
+        .write(&mut buf)?;
+        if let Some(trns) = trns {
+            PngChunk {
+                kind: PngChunkKind::Other(*PngChunkKind::TRNS),
+                data: trns.into(),
+            }
+            .write(&mut buf)?;
+        }
+        PngChunk {
+            kind: PngChunkKind::Idat,
+            data: compress_to_vec_zlib(filtered, level).into_boxed_slice(),
+        }
+        .write(&mut buf)?;
+        PngChunk::iend().write(&mut buf)?;
+
+        Ok(buf)
+    }
+
+    fn catpng_and_write(pngs: &[Vec<u8>], level: u8) -> Result<Vec<u8>, PngError> {
+        catpng_and_write_with_axis(pngs, level, ConcatAxis::Vertical)
+    }
+
+    fn catpng_and_write_with_axis(
+        pngs: &[Vec<u8>],
+        level: u8,
+        axis: ConcatAxis,
+    ) -> Result<Vec<u8>, PngError> {
+        let mut out = Vec::new();
+        write_png(
+            catpng(pngs.iter().map(Cursor::new), level, CrcMode::Strict, axis)?,
+            &mut out,
+        )?;
+        Ok(out)
+    }
+
+    #[test]
+    fn concat_1() -> Result<(), PngError> {
+        let ihdr = ihdr! {3, 1, 8, 0, 0, 0, 0};
+        let buf = png_vec(ihdr, &as_filtered(&[1, 2, 3], 3), 0)?;
+        let expected = buf.clone();
+
+        let out = catpng_and_write(&[buf], 0)?;
+        assert_eq!(&out, &expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn concat_with_split_idat_chunks() -> Result<(), PngError> {
+        let ihdr1 = ihdr! {3, 1, 8, 0, 0, 0, 0};
+        let ihdr2 = ihdr! {3, 2, 8, 0, 0, 0, 0};
+        let buf1 = png_vec_split_idat(ihdr1, &as_filtered(&[1, 2, 3], 3), 0, 3)?;
+        let buf2 = png_vec_split_idat(ihdr2, &as_filtered(&[4, 5, 6, 7, 8, 9], 3), 0, 2)?;
+
+        let expected_ihdr = ihdr! {3, 3, 8, 0, 0, 0, 0};
+        let expected = png_vec(
+            expected_ihdr,
+            &as_filtered(&[1, 2, 3, 4, 5, 6, 7, 8, 9], 3),
+            0,
+        )?;
+
+        let out = catpng_and_write(&[buf1, buf2], 0)?;
+        assert_eq!(&out, &expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn concat_2() -> Result<(), PngError> {
+        let ihdr1 = ihdr! {3, 1, 8, 0, 0, 0, 0};
+        let ihdr2 = ihdr! {3, 2, 8, 0, 0, 0, 0};
+        let buf1 = png_vec(ihdr1, &as_filtered(&[1, 2, 3], 3), 0)?;
+        let buf2 = png_vec(ihdr2, &as_filtered(&[4, 5, 6, 7, 8, 9], 3), 0)?;
+
+        let expected_ihdr = ihdr! {3, 3, 8, 0, 0, 0, 0};
+        let expected = png_vec(
+            expected_ihdr,
+            &as_filtered(&[1, 2, 3, 4, 5, 6, 7, 8, 9], 3),
+            0,
+        )?;
+
+        let out = catpng_and_write(&[buf1, buf2], 0)?;
+        assert_eq!(&out, &expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn concat_preserves_shared_palette() -> Result<(), PngError> {
+        let ihdr1 = ihdr! {2, 1, 8, 3, 0, 0, 0};
+        let ihdr2 = ihdr! {2, 2, 8, 3, 0, 0, 0};
+        let palette: [u8; 6] = [255, 0, 0, 0, 255, 0];
+
+        let buf1 = png_vec_with_palette(ihdr1, &as_filtered(&[0, 1], 2), &palette, None, 0)?;
+        let buf2 = png_vec_with_palette(ihdr2, &as_filtered(&[1, 0, 0, 1], 2), &palette, None, 0)?;
+
+        let (out_ihdr, out_ancillary, _) = catpng(
+            [Cursor::new(&buf1), Cursor::new(&buf2)],
+            0,
+            CrcMode::Strict,
+            ConcatAxis::Vertical,
+        )?;
+
+        assert_eq!(out_ihdr.height, 3);
+        assert_eq!(out_ancillary.len(), 1);
+        assert_eq!(
+            out_ancillary[0].kind,
+            PngChunkKind::Other(*PngChunkKind::PLTE)
+        );
+        assert_eq!(out_ancillary[0].data.as_ref(), &palette);
+
+        Ok(())
+    }
+
+    #[test]
+    fn concat_rejects_mismatched_palette() -> Result<(), PngError> {
+        let ihdr1 = ihdr! {2, 1, 8, 3, 0, 0, 0};
+        let ihdr2 = ihdr! {2, 1, 8, 3, 0, 0, 0};
+        let buf1 = png_vec_with_palette(
+            ihdr1,
+            &as_filtered(&[0, 1], 2),
+            &[255, 0, 0, 0, 255, 0],
+            None,
+            0,
+        )?;
+        let buf2 = png_vec_with_palette(
+            ihdr2,
+            &as_filtered(&[0, 1], 2),
+            &[0, 0, 255, 255, 255, 0],
+            None,
+            0,
+        )?;
+
+        let err = catpng(
+            [Cursor::new(&buf1), Cursor::new(&buf2)],
+            0,
+            CrcMode::Strict,
+            ConcatAxis::Vertical,
+        )
+        .unwrap_err();
+
+        assert!(matches!(err, PngError::UnequalPalette));
+
+        Ok(())
+    }
+
+    #[test]
+    fn concat_allows_mismatched_suggested_palette_on_non_indexed() -> Result<(), PngError> {
+        // PLTE on an RGB image is only a suggested palette, not a spec requirement to match
+        // across inputs, so this should concatenate fine rather than bailing with UnequalPalette.
+        let ihdr1 = ihdr! {2, 1, 8, 2, 0, 0, 0};
+        let ihdr2 = ihdr! {2, 1, 8, 2, 0, 0, 0};
+        let buf1 = png_vec_with_palette(
+            ihdr1,
+            &as_filtered(&[1, 2, 3, 4, 5, 6], 6),
+            &[255, 0, 0, 0, 255, 0],
+            None,
+            0,
+        )?;
+        let buf2 = png_vec_with_palette(
+            ihdr2,
+            &as_filtered(&[7, 8, 9, 10, 11, 12], 6),
+            &[0, 0, 255, 255, 255, 0],
+            None,
+            0,
+        )?;
+
+        let out = catpng(
+            [Cursor::new(&buf1), Cursor::new(&buf2)],
+            0,
+            CrcMode::Strict,
+            ConcatAxis::Vertical,
+        )?;
+
+        assert_eq!(out.0.height, 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn strict_mode_rejects_bad_crc() -> Result<(), PngError> {
+        let ihdr = ihdr! {3, 1, 8, 0, 0, 0, 0};
+        let mut buf = png_vec(ihdr, &as_filtered(&[1, 2, 3], 3), 0)?;
+
+        // Flip a byte in the IDAT chunk's stored CRC, which sits in the last 4 bytes before IEND.
+        let crc_pos = buf.len() - 12 - 4; // IEND is a fixed 12 bytes; IDAT's CRC is the 4 before it
+        buf[crc_pos] ^= 0xff;
+
+        let err = catpng(
+            [Cursor::new(&buf)],
+            0,
+            CrcMode::Strict,
+            ConcatAxis::Vertical,
+        )
+        .unwrap_err();
+        assert!(matches!(err, PngError::BadCrc { .. }));
+
+        Ok(())
+    }
+
+    #[test]
+    fn lenient_mode_ignores_bad_crc() -> Result<(), PngError> {
+        let ihdr = ihdr! {3, 1, 8, 0, 0, 0, 0};
+        let mut buf = png_vec(ihdr, &as_filtered(&[1, 2, 3], 3), 0)?;
+
+        let crc_pos = buf.len() - 12 - 4; // IEND is a fixed 12 bytes; IDAT's CRC is the 4 before it
+        buf[crc_pos] ^= 0xff;
+
+        let (out_ihdr, _, _) = catpng(
+            [Cursor::new(&buf)],
+            0,
+            CrcMode::Lenient,
+            ConcatAxis::Vertical,
+        )?;
+        assert_eq!(out_ihdr.width, 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn unfilter_then_refilter_round_trips_all_filter_types() -> Result<(), PngError> {
+        let ihdr = ihdr! {4, 5, 8, 0, 0, 0, 0};
+        let raw: Vec<u8> = (1..=20).collect();
+
+        // Build a filtered buffer exercising each filter type once.
+        let mut filtered = Vec::new();
+        for (row_idx, row) in raw.chunks(4).enumerate() {
+            let prev: Vec<u8> = if row_idx == 0 {
+                vec![0; 4]
+            } else {
+                raw[(row_idx - 1) * 4..row_idx * 4].to_vec()
+            };
+
+            match row_idx {
+                0 => {
+                    filtered.push(0);
+                    filtered.extend_from_slice(row);
+                }
+                1 => {
+                    filtered.push(1);
+                    let mut a = 0u8;
+                    for &x in row {
+                        filtered.push(x.wrapping_sub(a));
+                        a = x;
+                    }
+                }
+                2 => {
+                    filtered.push(2);
+                    for (x, b) in row.iter().zip(prev.iter()) {
+                        filtered.push(x.wrapping_sub(*b));
+                    }
+                }
+                3 => {
+                    filtered.push(3);
+                    let mut a = 0u8;
+                    for (&x, &b) in row.iter().zip(prev.iter()) {
+                        filtered.push(x.wrapping_sub(((a as u16 + b as u16) / 2) as u8));
+                        a = x;
+                    }
+                }
+                _ => {
+                    filtered.push(4);
+                    let mut a = 0u8;
+                    let mut c = 0u8;
+                    for (&x, &b) in row.iter().zip(prev.iter()) {
+                        filtered.push(x.wrapping_sub(paeth_predictor(a, b, c)));
+                        a = x;
+                        c = b;
+                    }
+                }
+            }
+        }
+
+        let raw_out = unfilter_scanlines(&ihdr, &filtered)?;
+        assert_eq!(raw_out, raw);
+
+        let refiltered = filter_scanlines(&ihdr, &raw_out)?;
+        assert_eq!(unfilter_scanlines(&ihdr, &refiltered)?, raw);
+
+        Ok(())
+    }
+
+    #[test]
+    fn concat_horizontal_byte_aligned() -> Result<(), PngError> {
+        let ihdr1 = ihdr! {2, 2, 8, 0, 0, 0, 0};
+        let ihdr2 = ihdr! {3, 2, 8, 0, 0, 0, 0};
+        let buf1 = png_vec(ihdr1, &as_filtered(&[1, 2, 3, 4], 2), 0)?;
+        let buf2 = png_vec(ihdr2, &as_filtered(&[5, 6, 7, 8, 9, 10], 3), 0)?;
+
+        let expected_ihdr = ihdr! {5, 2, 8, 0, 0, 0, 0};
+        let expected = png_vec(
+            expected_ihdr,
+            &as_filtered(&[1, 2, 5, 6, 7, 3, 4, 8, 9, 10], 5),
+            0,
+        )?;
+
+        let out = catpng_and_write_with_axis(&[buf1, buf2], 0, ConcatAxis::Horizontal)?;
+        assert_eq!(&out, &expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn concat_horizontal_rejects_unequal_height() -> Result<(), PngError> {
+        let ihdr1 = ihdr! {2, 1, 8, 0, 0, 0, 0};
+        let ihdr2 = ihdr! {2, 2, 8, 0, 0, 0, 0};
+        let buf1 = png_vec(ihdr1, &as_filtered(&[1, 2], 2), 0)?;
+        let buf2 = png_vec(ihdr2, &as_filtered(&[3, 4, 5, 6], 2), 0)?;
+
+        let err = catpng_and_write_with_axis(&[buf1, buf2], 0, ConcatAxis::Horizontal).unwrap_err();
+        assert!(matches!(err, PngError::UnequalHeight));
+
+        Ok(())
+    }
+
+    #[test]
+    fn concat_vertical_rejects_mismatched_color_format() -> Result<(), PngError> {
+        let ihdr1 = ihdr! {4, 1, 8, 0, 0, 0, 0};
+        let ihdr2 = ihdr! {4, 1, 8, 2, 0, 0, 0};
+        let buf1 = png_vec(ihdr1, &as_filtered(&[1, 2, 3, 4], 4), 0)?;
+        let buf2 = png_vec(
+            ihdr2,
+            &as_filtered(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12], 12),
+            0,
+        )?;
+
+        let err = catpng_and_write(&[buf1, buf2], 0).unwrap_err();
+        assert!(matches!(err, PngError::UnequalColorFormat));
+
+        Ok(())
+    }
+
+    #[test]
+    fn concat_horizontal_sub_byte_bit_depth() -> Result<(), PngError> {
+        // Two 4-pixel-wide, bit_depth=1 rows, each padded to a whole byte; concatenating them
+        // must drop the padding bits instead of embedding them mid-row.
+        let ihdr1 = ihdr! {4, 1, 1, 0, 0, 0, 0};
+        let ihdr2 = ihdr! {4, 1, 1, 0, 0, 0, 0};
+        let buf1 = png_vec(ihdr1, &as_filtered(&[0b1010_0000], 1), 0)?;
+        let buf2 = png_vec(ihdr2, &as_filtered(&[0b0101_0000], 1), 0)?;
+
+        let expected_ihdr = ihdr! {8, 1, 1, 0, 0, 0, 0};
+        let expected = png_vec(expected_ihdr, &as_filtered(&[0b1010_0101], 1), 0)?;
+
+        let out = catpng_and_write_with_axis(&[buf1, buf2], 0, ConcatAxis::Horizontal)?;
+        assert_eq!(&out, &expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn apng_builds_sequence_and_frames() -> Result<(), PngError> {
+        let ihdr1 = ihdr! {2, 1, 8, 0, 0, 0, 0};
+        let ihdr2 = ihdr! {2, 1, 8, 0, 0, 0, 0};
+        let buf1 = png_vec(ihdr1, &as_filtered(&[1, 2], 2), 0)?;
+        let buf2 = png_vec(ihdr2, &as_filtered(&[3, 4], 2), 0)?;
+
+        let (out_ihdr, body) = apng(
+            [Cursor::new(&buf1), Cursor::new(&buf2)],
+            0,
+            CrcMode::Strict,
+            1,
+            10,
+        )?;
+
+        assert_eq!(out_ihdr.width, 2);
+        assert_eq!(out_ihdr.height, 1);
+        assert_eq!(body.len(), 5);
+
+        assert_eq!(body[0].kind, PngChunkKind::Other(*PngChunkKind::ACTL));
+        assert_eq!(&body[0].data[0..4], &2u32.to_be_bytes()); // num_frames
+        assert_eq!(&body[0].data[4..8], &0u32.to_be_bytes()); // num_plays
+
+        assert_eq!(body[1].kind, PngChunkKind::Other(*PngChunkKind::FCTL));
+        assert_eq!(&body[1].data[0..4], &0u32.to_be_bytes()); // sequence_number
+        assert_eq!(&body[1].data[4..8], &2u32.to_be_bytes()); // width
+        assert_eq!(&body[1].data[20..22], &1u16.to_be_bytes()); // delay_num
+        assert_eq!(&body[1].data[22..24], &10u16.to_be_bytes()); // delay_den
+
+        assert_eq!(body[2].kind, PngChunkKind::Idat);
+
+        assert_eq!(body[3].kind, PngChunkKind::Other(*PngChunkKind::FCTL));
+        assert_eq!(&body[3].data[0..4], &1u32.to_be_bytes()); // sequence_number
+
+        assert_eq!(body[4].kind, PngChunkKind::Other(*PngChunkKind::FDAT));
+        assert_eq!(&body[4].data[0..4], &2u32.to_be_bytes()); // sequence_number
+
+        Ok(())
+    }
+
+    #[test]
+    fn apng_rejects_mismatched_dimensions() -> Result<(), PngError> {
+        let ihdr1 = ihdr! {2, 1, 8, 0, 0, 0, 0};
+        let ihdr2 = ihdr! {3, 1, 8, 0, 0, 0, 0};
+        let buf1 = png_vec(ihdr1, &as_filtered(&[1, 2], 2), 0)?;
+        let buf2 = png_vec(ihdr2, &as_filtered(&[3, 4, 5], 3), 0)?;
+
+        let err = apng(
+            [Cursor::new(&buf1), Cursor::new(&buf2)],
+            0,
+            CrcMode::Strict,
+            100,
+            1000,
+        )
+        .unwrap_err();
+
+        assert!(matches!(err, PngError::UnequalWidth));
+
+        Ok(())
+    }
+
+    #[test]
+    fn apng_preserves_shared_palette() -> Result<(), PngError> {
+        let ihdr1 = ihdr! {2, 1, 8, 3, 0, 0, 0};
+        let ihdr2 = ihdr! {2, 1, 8, 3, 0, 0, 0};
+        let palette: [u8; 6] = [255, 0, 0, 0, 255, 0];
+
+        let buf1 = png_vec_with_palette(ihdr1, &as_filtered(&[0, 1], 2), &palette, None, 0)?;
+        let buf2 = png_vec_with_palette(ihdr2, &as_filtered(&[1, 0], 2), &palette, None, 0)?;
+
+        let (_, body) = apng(
+            [Cursor::new(&buf1), Cursor::new(&buf2)],
+            0,
+            CrcMode::Strict,
+            100,
+            1000,
+        )?;
+
+        assert_eq!(body.len(), 6);
+        assert_eq!(body[0].kind, PngChunkKind::Other(*PngChunkKind::PLTE));
+        assert_eq!(body[0].data.as_ref(), &palette);
+        assert_eq!(body[1].kind, PngChunkKind::Other(*PngChunkKind::ACTL));
+
+        Ok(())
+    }
+
+    #[test]
+    fn apng_rejects_indexed_output_without_palette() -> Result<(), PngError> {
+        let ihdr = ihdr! {2, 1, 8, 3, 0, 0, 0};
+        let buf = png_vec(ihdr, &as_filtered(&[0, 1], 2), 0)?;
+
+        let err = apng([Cursor::new(&buf)], 0, CrcMode::Strict, 100, 1000).unwrap_err();
+
+        assert!(matches!(err, PngError::MissingPalette));
+
+        Ok(())
+    }
+
+    #[test]
+    fn required_bytes_matches_scanline_layout() {
+        // RGB (3 channels), width 3, height 4: row_stride = ceil(3*8*3/8) = 9, scanline_len = 10.
+        let ihdr = ihdr! {3, 4, 8, 2, 0, 0, 0};
+        assert_eq!(ihdr.required_bytes().unwrap(), 40);
+    }
+
+    #[test]
+    fn catpng_into_writes_combined_output_into_caller_buffer() -> Result<(), PngError> {
+        let ihdr1 = ihdr! {3, 1, 8, 0, 0, 0, 0};
+        let ihdr2 = ihdr! {3, 2, 8, 0, 0, 0, 0};
+        let buf1 = png_vec(ihdr1, &as_filtered(&[1, 2, 3], 3), 0)?;
+        let buf2 = png_vec(ihdr2, &as_filtered(&[4, 5, 6, 7, 8, 9], 3), 0)?;
+
+        let expected_ihdr = ihdr! {3, 3, 8, 0, 0, 0, 0};
+        let mut buf = vec![0u8; expected_ihdr.required_bytes()?];
+
+        let (out_ihdr, _, out_idat) = catpng_into(
+            [Cursor::new(&buf1), Cursor::new(&buf2)],
+            0,
+            CrcMode::Strict,
+            ConcatAxis::Vertical,
+            &mut buf,
+        )?;
+
+        assert_eq!(out_ihdr.height, 3);
+        assert_eq!(
+            decompress_to_vec_zlib(&out_idat.data).unwrap(),
+            as_filtered(&[1, 2, 3, 4, 5, 6, 7, 8, 9], 3)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn catpng_into_rejects_undersized_buffer() -> Result<(), PngError> {
+        let ihdr = ihdr! {3, 1, 8, 0, 0, 0, 0};
+        let buf = png_vec(ihdr, &as_filtered(&[1, 2, 3], 3), 0)?;
+
+        let mut undersized = vec![0u8; 1];
+        let err = catpng_into(
+            [Cursor::new(&buf)],
+            0,
+            CrcMode::Strict,
+            ConcatAxis::Vertical,
+            &mut undersized,
+        )
+        .unwrap_err();
+
+        assert!(matches!(
+            err,
+            PngError::BufferTooSmall {
+                required: 4,
+                actual: 1
+            }
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn catpng_rejects_indexed_output_without_palette() -> Result<(), PngError> {
+        let ihdr = ihdr! {2, 1, 8, 3, 0, 0, 0};
+        let buf = png_vec(ihdr, &as_filtered(&[0, 1], 2), 0)?;
+
+        let err = catpng(
+            [Cursor::new(&buf)],
+            0,
+            CrcMode::Strict,
+            ConcatAxis::Vertical,
+        )
+        .unwrap_err();
+
+        assert!(matches!(err, PngError::MissingPalette));
+
+        Ok(())
+    }
+
+    #[test]
+    fn catpng_rejects_empty_input() {
+        let err = catpng(
+            Vec::<Cursor<&[u8]>>::new(),
+            0,
+            CrcMode::Strict,
+            ConcatAxis::Vertical,
+        )
+        .unwrap_err();
+
+        assert!(matches!(err, PngError::NoInputImages));
+    }
+}